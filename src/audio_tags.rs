@@ -0,0 +1,494 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2013 Jeremy Letang (letang.jeremy@gmail.com)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! The tags (metadata) attached to a sound file.
+
+use std::io::File;
+
+use sndfile::SndFile;
+
+/// Where a `Tags` value's fields were read from, for callers that care
+/// when a WAV carries both a `LIST/INFO` chunk and an embedded ID3v2 tag.
+#[deriving(Clone, PartialEq, Show)]
+pub enum TagSource {
+    /// libsndfile's own metadata accessors (the original source).
+    Native,
+    /// A RIFF `LIST` chunk of type `INFO`.
+    RiffInfo,
+    /// An embedded ID3v2 tag (an `id3 ` / `ID3 ` chunk in a WAV, or a
+    /// leading frame in other containers).
+    Id3v2
+}
+
+/**
+ * The metadata of a sound file.
+ */
+#[deriving(Clone)]
+pub struct Tags {
+    /// The title of the sound
+    pub title: Option<String>,
+    /// The artist of the sound
+    pub artist: Option<String>,
+    /// The album the sound belongs to
+    pub album: Option<String>,
+    /// The producer of the sound
+    pub producer: Option<String>,
+    /// A free-form comment
+    pub comment: Option<String>,
+    /// The genre of the sound
+    pub genre: Option<String>,
+    /// The last source merged in: `Id3v2` if an ID3v2 tag was found,
+    /// else `RiffInfo` if an INFO list was found, else `Native`
+    pub source: TagSource
+}
+
+impl Tags {
+    /// An empty Tags, as returned when no metadata could be found at all.
+    pub fn new() -> Tags {
+        Tags {
+            title: None,
+            artist: None,
+            album: None,
+            producer: None,
+            comment: None,
+            genre: None,
+            source: Native
+        }
+    }
+
+    /// Overlay `other`'s fields onto `self`, keeping `self`'s value for
+    /// any field `other` leaves unset. Used to merge the INFO and ID3v2
+    /// layers: `info.merge(id3)` lets ID3v2 win the fields it actually
+    /// sets, per the request's "default to ID3v2 on conflict" rule.
+    fn merge(self, other: Tags) -> Tags {
+        Tags {
+            title: other.title.or(self.title),
+            artist: other.artist.or(self.artist),
+            album: other.album.or(self.album),
+            producer: other.producer.or(self.producer),
+            comment: other.comment.or(self.comment),
+            genre: other.genre.or(self.genre),
+            source: other.source
+        }
+    }
+}
+
+/// Implemented by anything that carries a `Tags`.
+pub trait AudioTags {
+    /// Get the tags.
+    fn get_tags(&self) -> Tags;
+}
+
+/**
+ * Build the Tags for a sound file: start from libsndfile's own
+ * metadata, then merge in a RIFF `LIST/INFO` chunk and then an embedded
+ * ID3v2 tag, since libsndfile only surfaces a handful of string fields
+ * itself. Each later layer overrides the fields it sets, so ID3v2 wins
+ * over INFO, and both win over libsndfile.
+ *
+ * # Return
+ * The Tags extracted for the file, empty if nothing could be read.
+ */
+pub fn get_sound_tags(file: &SndFile) -> Tags {
+    let mut tags = sndfile_tags(file);
+
+    if let Some(path) = file.get_path() {
+        if let Some(info) = read_riff_info_tags(path.as_slice()) {
+            tags = tags.merge(info);
+        }
+        if let Some(id3) = read_id3v2_tags(path.as_slice()) {
+            tags = tags.merge(id3);
+        }
+    }
+
+    tags
+}
+
+/// Read the handful of string fields libsndfile itself exposes.
+fn sndfile_tags(file: &SndFile) -> Tags {
+    let mut tags = Tags::new();
+    tags.title = file.get_string(::sndfile::ffi::SF_STR_TITLE);
+    tags.artist = file.get_string(::sndfile::ffi::SF_STR_ARTIST);
+    tags.album = file.get_string(::sndfile::ffi::SF_STR_ALBUM);
+    tags.producer = file.get_string(::sndfile::ffi::SF_STR_SOFTWARE);
+    tags.comment = file.get_string(::sndfile::ffi::SF_STR_COMMENT);
+    tags.genre = file.get_string(::sndfile::ffi::SF_STR_GENRE);
+    tags.source = Native;
+    tags
+}
+
+/**
+ * Walk a WAV's top-level RIFF chunks looking for a `LIST` chunk of type
+ * `INFO`, and map its `INAM`/`IART`/`IPRD`/`ICMT`/`IGNR` sub-chunks onto
+ * `Tags`.
+ *
+ * # Return
+ * Some(Tags) if an INFO list was found, None if the file isn't a RIFF
+ * WAV or carries no INFO chunk.
+ */
+fn read_riff_info_tags(path: &str) -> Option<Tags> {
+    let mut file = match File::open(&Path::new(path)) {
+        Ok(f) => f,
+        Err(_) => return None
+    };
+
+    let riff = file.read_exact(4).unwrap_or(Vec::new());
+    if riff.as_slice() != b"RIFF" {
+        return None;
+    }
+    file.read_exact(4).ok(); // chunk size, unused
+    let wave = file.read_exact(4).unwrap_or(Vec::new());
+    if wave.as_slice() != b"WAVE" {
+        return None;
+    }
+
+    loop {
+        let id = match file.read_exact(4) {
+            Ok(id) => id,
+            Err(_) => break
+        };
+        let size = match file.read_le_u32() {
+            Ok(size) => size,
+            Err(_) => break
+        };
+
+        if id.as_slice() == b"LIST" {
+            if size < 4 {
+                // Malformed chunk: not even room for the list type field.
+                // Bail on this chunk rather than underflowing `size - 4`
+                // into a multi-gigabyte read.
+                let padded = size + (size & 1);
+                file.seek(padded as i64, ::std::io::SeekCur).ok();
+                continue;
+            }
+
+            let kind = file.read_exact(4).unwrap_or(Vec::new());
+            let remaining = size - 4;
+            if kind.as_slice() == b"INFO" {
+                let body = file.read_exact(remaining as uint).unwrap_or(Vec::new());
+                let mut tags = Tags::new();
+                tags.source = RiffInfo;
+                parse_info_subchunks(body.as_slice(), &mut tags);
+                return Some(tags);
+            } else {
+                let padded = remaining + (remaining & 1);
+                file.seek(padded as i64, ::std::io::SeekCur).ok();
+            }
+        } else {
+            // RIFF chunks are word-aligned; skip the pad byte on odd sizes.
+            let padded = size + (size & 1);
+            file.seek(padded as i64, ::std::io::SeekCur).ok();
+        }
+    }
+
+    None
+}
+
+/// Parse the `INAM`/`IART`/... sub-chunks of an already-extracted INFO
+/// list body into `tags`.
+fn parse_info_subchunks(mut body: &[u8], tags: &mut Tags) -> () {
+    while body.len() >= 8 {
+        let id = body.slice_to(4);
+        let size = (body[4] as u32) | (body[5] as u32 << 8) |
+                   (body[6] as u32 << 16) | (body[7] as u32 << 24);
+        let size = size as uint;
+        let padded = size + (size & 1);
+
+        if body.len() < 8 + size {
+            break;
+        }
+
+        let value = String::from_utf8_lossy(body.slice(8, 8 + size))
+            .trim_right_chars('\0').to_string();
+
+        match id {
+            b"INAM" => tags.title = Some(value),
+            b"IART" => tags.artist = Some(value),
+            b"IPRD" => tags.album = Some(value),
+            b"ICMT" => tags.comment = Some(value),
+            b"IGNR" => tags.genre = Some(value),
+            _ => {}
+        }
+
+        if body.len() < 8 + padded {
+            // The INFO body ended exactly on this (odd-sized) sub-chunk
+            // with no trailing pad byte actually present -- nothing left
+            // to parse, so stop instead of slicing past the end.
+            break;
+        }
+        body = body.slice_from(8 + padded);
+    }
+}
+
+/**
+ * Look for an embedded ID3v2 tag: either a WAV `id3 `/`ID3 ` RIFF
+ * chunk, or (for non-RIFF inputs) a tag starting at byte 0.
+ *
+ * Only the common text frames (`TIT2`, `TPE1`, `TALB`, `TCON`, `COMM`)
+ * are extracted; anything else in the tag is ignored.
+ *
+ * # Return
+ * Some(Tags) if an ID3v2 tag with at least one of the frames above was
+ * found, None otherwise.
+ */
+fn read_id3v2_tags(path: &str) -> Option<Tags> {
+    let mut file = match File::open(&Path::new(path)) {
+        Ok(f) => f,
+        Err(_) => return None
+    };
+
+    let riff = file.read_exact(4).unwrap_or(Vec::new());
+    if riff.as_slice() == b"RIFF" {
+        file.read_exact(4).ok();
+        file.read_exact(4).ok(); // WAVE
+
+        loop {
+            let id = match file.read_exact(4) {
+                Ok(id) => id,
+                Err(_) => return None
+            };
+            let size = match file.read_le_u32() {
+                Ok(size) => size,
+                Err(_) => return None
+            };
+
+            if id.as_slice() == b"id3 " || id.as_slice() == b"ID3 " {
+                let body = file.read_exact(size as uint).unwrap_or(Vec::new());
+                // The chunk holds a whole ID3v2 tag: skip its 10-byte
+                // header, as the non-RIFF branch below does.
+                if body.len() < 10 || body.slice_to(3) != b"ID3" {
+                    return None;
+                }
+                return parse_id3v2_frames(body.slice_from(10));
+            }
+
+            let padded = size + (size & 1);
+            file.seek(padded as i64, ::std::io::SeekCur).ok();
+        }
+    } else {
+        file.seek(0, ::std::io::SeekSet).ok();
+        let header = file.read_exact(10).unwrap_or(Vec::new());
+        if header.len() < 10 || header.slice_to(3) != b"ID3" {
+            return None;
+        }
+        let size = synchsafe_to_u32(header.slice(6, 10));
+        let body = file.read_exact(size as uint).unwrap_or(Vec::new());
+        parse_id3v2_frames(body.as_slice())
+    }
+}
+
+/// Decode a synchsafe 28-bit ID3v2 size (7 significant bits per byte).
+fn synchsafe_to_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32 << 21) | (bytes[1] as u32 << 14) |
+    (bytes[2] as u32 << 7)  | (bytes[3] as u32)
+}
+
+/// Parse the text frames of an ID3v2 tag body into a fresh `Tags`.
+fn parse_id3v2_frames(mut body: &[u8]) -> Option<Tags> {
+    let mut tags = Tags::new();
+    tags.source = Id3v2;
+    let mut found_any = false;
+
+    while body.len() >= 10 {
+        let id = body.slice_to(4);
+        let size = synchsafe_to_u32(body.slice(4, 8)) as uint;
+        if body.len() < 10 + size || size == 0 {
+            break;
+        }
+        let frame = body.slice(10, 10 + size);
+
+        let text = String::from_utf8_lossy(frame.slice_from(1))
+            .trim_right_chars('\0').to_string();
+
+        match id {
+            b"TIT2" => { tags.title = Some(text); found_any = true; },
+            b"TPE1" => { tags.artist = Some(text); found_any = true; },
+            b"TALB" => { tags.album = Some(text); found_any = true; },
+            b"TCON" => { tags.genre = Some(text); found_any = true; },
+            b"COMM" => { tags.comment = Some(text); found_any = true; },
+            _ => {}
+        }
+
+        body = body.slice_from(10 + size);
+    }
+
+    if found_any { Some(tags) } else { None }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Tags, Native, RiffInfo, Id3v2};
+    use super::{parse_info_subchunks, parse_id3v2_frames, synchsafe_to_u32, read_riff_info_tags};
+    use std::io::{File, TempDir, Writer};
+
+    /// Push a little-endian u32 onto `bytes`, RIFF/ID3v2-chunk-size style.
+    fn push_le_u32(bytes: &mut Vec<u8>, value: u32) -> () {
+        bytes.push((value & 0xff) as u8);
+        bytes.push(((value >> 8) & 0xff) as u8);
+        bytes.push(((value >> 16) & 0xff) as u8);
+        bytes.push(((value >> 24) & 0xff) as u8);
+    }
+
+    /// Push a synchsafe (7-bits-per-byte) u32, ID3v2-frame-size style.
+    fn push_synchsafe_u32(bytes: &mut Vec<u8>, value: u32) -> () {
+        bytes.push(((value >> 21) & 0x7f) as u8);
+        bytes.push(((value >> 14) & 0x7f) as u8);
+        bytes.push(((value >> 7) & 0x7f) as u8);
+        bytes.push((value & 0x7f) as u8);
+    }
+
+    #[test]
+    fn synchsafe_to_u32_decodes_7_bits_per_byte() -> () {
+        // 2 << 7 | 1 == 257, encoded across the low 7 bits of each byte.
+        assert_eq!(synchsafe_to_u32(&[0u8, 0u8, 2u8, 1u8]), 257u32);
+    }
+
+    #[test]
+    fn parse_info_subchunks_reads_even_and_odd_sized_entries() -> () {
+        let mut body = Vec::new();
+        body.push_all(b"INAM");
+        push_le_u32(&mut body, 2);
+        body.push_all(b"Hi"); // even size, no pad byte
+
+        body.push_all(b"IART");
+        push_le_u32(&mut body, 3);
+        body.push_all(b"Bob"); // odd size, followed by a pad byte
+        body.push(0u8);
+
+        let mut tags = Tags::new();
+        parse_info_subchunks(body.as_slice(), &mut tags);
+
+        assert_eq!(tags.title, Some("Hi".to_string()));
+        assert_eq!(tags.artist, Some("Bob".to_string()));
+    }
+
+    #[test]
+    fn parse_info_subchunks_does_not_panic_on_missing_trailing_pad_byte() -> () {
+        // The INFO body ends exactly on an odd-sized sub-chunk, with no
+        // trailing pad byte actually present in the slice.
+        let mut body = Vec::new();
+        body.push_all(b"ICMT");
+        push_le_u32(&mut body, 3);
+        body.push_all(b"abc");
+
+        let mut tags = Tags::new();
+        parse_info_subchunks(body.as_slice(), &mut tags);
+
+        assert_eq!(tags.comment, Some("abc".to_string()));
+    }
+
+    #[test]
+    fn parse_id3v2_frames_reads_text_frames() -> () {
+        let mut body = Vec::new();
+        body.push_all(b"TIT2");
+        push_synchsafe_u32(&mut body, 5);
+        body.push(0u8); // frame flags
+        body.push(0u8);
+        body.push(0u8); // text encoding byte
+        body.push_all(b"Song");
+
+        let tags = parse_id3v2_frames(body.as_slice()).unwrap();
+
+        assert_eq!(tags.title, Some("Song".to_string()));
+        assert_eq!(tags.source, Id3v2);
+    }
+
+    #[test]
+    fn parse_id3v2_frames_returns_none_when_nothing_recognized() -> () {
+        let mut body = Vec::new();
+        body.push_all(b"XXXX");
+        push_synchsafe_u32(&mut body, 1);
+        body.push(0u8); // frame flags
+        body.push(0u8);
+        body.push(0u8);
+
+        assert!(parse_id3v2_frames(body.as_slice()).is_none());
+    }
+
+    #[test]
+    fn read_riff_info_tags_reads_info_list_from_a_wav() -> () {
+        let dir = TempDir::new("audio_tags_test").unwrap();
+        let path = dir.path().join("tagged.wav");
+
+        let mut info_body = Vec::new();
+        info_body.push_all(b"INFO");
+        info_body.push_all(b"INAM");
+        push_le_u32(&mut info_body, 2);
+        info_body.push_all(b"Hi");
+
+        let mut wav = Vec::new();
+        wav.push_all(b"RIFF");
+        push_le_u32(&mut wav, 0); // overall size, unused by the reader
+        wav.push_all(b"WAVE");
+        wav.push_all(b"LIST");
+        push_le_u32(&mut wav, info_body.len() as u32);
+        wav.push_all(info_body.as_slice());
+
+        {
+            let mut file = File::create(&path).unwrap();
+            file.write(wav.as_slice()).unwrap();
+        }
+
+        let tags = read_riff_info_tags(path.as_str().unwrap()).unwrap();
+        assert_eq!(tags.title, Some("Hi".to_string()));
+        assert_eq!(tags.source, RiffInfo);
+    }
+
+    #[test]
+    fn tags_new_is_empty_with_native_source() -> () {
+        let tags = Tags::new();
+
+        assert_eq!(tags.title, None);
+        assert_eq!(tags.artist, None);
+        assert_eq!(tags.album, None);
+        assert_eq!(tags.producer, None);
+        assert_eq!(tags.comment, None);
+        assert_eq!(tags.genre, None);
+        assert_eq!(tags.source, Native);
+    }
+
+    #[test]
+    fn tags_merge_prefers_later_source_when_set() -> () {
+        let mut info = Tags::new();
+        info.title = Some("Info Title".to_string());
+        info.source = RiffInfo;
+
+        let mut id3 = Tags::new();
+        id3.title = Some("Id3 Title".to_string());
+        id3.source = Id3v2;
+
+        let merged = info.merge(id3);
+        assert_eq!(merged.title, Some("Id3 Title".to_string()));
+        assert_eq!(merged.source, Id3v2);
+    }
+
+    #[test]
+    fn tags_merge_keeps_earlier_field_when_later_unset() -> () {
+        let mut info = Tags::new();
+        info.artist = Some("Info Artist".to_string());
+        info.source = RiffInfo;
+
+        let id3 = Tags::new();
+
+        let merged = info.merge(id3);
+        assert_eq!(merged.artist, Some("Info Artist".to_string()));
+    }
+}
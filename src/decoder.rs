@@ -0,0 +1,255 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2013 Jeremy Letang (letang.jeremy@gmail.com)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Pure-Rust decoder backends, selected by file extension, so loading
+//! doesn't always have to go through libsndfile.
+
+use std::path::Path;
+
+use sndfile::{SndFile, SndInfo, Read};
+
+/**
+ * A source of interleaved i16 samples, decoded from some file format.
+ *
+ * Every decoder backend (the libsndfile-backed one as well as the
+ * pure-Rust FLAC/OGG/MP3 ones) implements this so `SoundData` can drive
+ * them all the same way: ask for the layout, then drain samples into a
+ * buffer sized for it.
+ */
+pub trait Decoder {
+    /// Read up to `out.len()` samples, returning how many were written.
+    fn read_samples(&mut self, out: &mut [i16]) -> uint;
+    /// Number of interleaved channels in the decoded stream.
+    fn channels(&self) -> i32;
+    /// Sample rate of the decoded stream, in Hz.
+    fn sample_rate(&self) -> i32;
+    /// Total number of frames (samples per channel) in the stream, or 0
+    /// when the backend doesn't know it up front (OGG and MP3 always
+    /// return 0).
+    fn frames(&self) -> i64;
+}
+
+/// Decodes through the existing libsndfile binding; used for `.wav` and
+/// `.aiff`, and as the fallback for anything not recognized below.
+pub struct SndFileDecoder {
+    file: SndFile,
+    info: SndInfo
+}
+
+impl SndFileDecoder {
+    fn new(path: &str) -> Option<SndFileDecoder> {
+        match SndFile::new(path, Read) {
+            Ok(mut file) => {
+                let info = file.get_sndinfo();
+                Some(SndFileDecoder { file: file, info: info })
+            },
+            Err(err) => { println!("{}", err); None }
+        }
+    }
+}
+
+impl Decoder for SndFileDecoder {
+    fn read_samples(&mut self, out: &mut [i16]) -> uint {
+        self.file.read_i16(out, out.len() as i64) as uint
+    }
+
+    fn channels(&self) -> i32 { self.info.channels }
+    fn sample_rate(&self) -> i32 { self.info.samplerate }
+    fn frames(&self) -> i64 { self.info.frames }
+}
+
+/// Decodes `.flac` with the pure-Rust `claxon` crate.
+pub struct FlacDecoder {
+    reader: ::claxon::FlacReader<::std::io::File>,
+    channels: i32,
+    sample_rate: i32,
+    frames: i64
+}
+
+impl FlacDecoder {
+    fn new(path: &str) -> Option<FlacDecoder> {
+        match ::claxon::FlacReader::open(path) {
+            Ok(reader) => {
+                let info = reader.streaminfo();
+                Some(FlacDecoder {
+                    channels: info.channels as i32,
+                    sample_rate: info.sample_rate as i32,
+                    frames: info.samples.unwrap_or(0) as i64,
+                    reader: reader
+                })
+            },
+            Err(err) => { println!("{}", err); None }
+        }
+    }
+}
+
+impl Decoder for FlacDecoder {
+    fn read_samples(&mut self, out: &mut [i16]) -> uint {
+        let channels = self.channels as uint;
+        let mut written = 0u;
+        let mut frame_reader = self.reader.blocks();
+        while written < out.len() {
+            match frame_reader.read_next_or_eof(Vec::new()) {
+                Ok(Some(block)) => {
+                    // Walk frame by frame so every channel lands in the
+                    // output interleaved, not just channel 0.
+                    for s in range(0u, block.len() as uint) {
+                        if written >= out.len() { break; }
+                        for ch in range(0u, channels) {
+                            if written >= out.len() { break; }
+                            out[written] = block.sample(ch as u32, s as u32) as i16;
+                            written += 1;
+                        }
+                    }
+                },
+                _ => break
+            }
+        }
+        written
+    }
+
+    fn channels(&self) -> i32 { self.channels }
+    fn sample_rate(&self) -> i32 { self.sample_rate }
+    fn frames(&self) -> i64 { self.frames }
+}
+
+/// Decodes `.ogg` (Vorbis) with the pure-Rust `lewton` crate.
+pub struct OggDecoder {
+    reader: ::lewton::inside_ogg::OggStreamReader<::std::io::File>,
+    pending: Vec<i16>,
+    channels: i32,
+    sample_rate: i32
+}
+
+impl OggDecoder {
+    fn new(path: &str) -> Option<OggDecoder> {
+        let file = match ::std::io::File::open(&Path::new(path)) {
+            Ok(f) => f,
+            Err(err) => { println!("{}", err); return None; }
+        };
+        match ::lewton::inside_ogg::OggStreamReader::new(file) {
+            Ok(reader) => {
+                let channels = reader.ident_hdr.audio_channels as i32;
+                let sample_rate = reader.ident_hdr.audio_sample_rate as i32;
+                Some(OggDecoder { reader: reader, pending: Vec::new(), channels: channels, sample_rate: sample_rate })
+            },
+            Err(err) => { println!("{}", err); None }
+        }
+    }
+}
+
+impl Decoder for OggDecoder {
+    fn read_samples(&mut self, out: &mut [i16]) -> uint {
+        let mut written = 0u;
+        while written < out.len() {
+            if self.pending.len() == 0 {
+                match self.reader.read_dec_packet_itl() {
+                    Ok(Some(packet)) => self.pending = packet,
+                    _ => break
+                }
+            }
+            let n = ::std::cmp::min(out.len() - written, self.pending.len());
+            for i in range(0u, n) {
+                out[written + i] = self.pending[i];
+            }
+            written += n;
+            self.pending = self.pending.slice_from(n).to_vec();
+        }
+        written
+    }
+
+    fn channels(&self) -> i32 { self.channels }
+    fn sample_rate(&self) -> i32 { self.sample_rate }
+    fn frames(&self) -> i64 { 0 }
+}
+
+/// Decodes `.mp3` with the pure-Rust `minimp3` crate.
+pub struct Mp3Decoder {
+    decoder: ::minimp3::Decoder<::std::io::File>,
+    pending: Vec<i16>,
+    channels: i32,
+    sample_rate: i32
+}
+
+impl Mp3Decoder {
+    fn new(path: &str) -> Option<Mp3Decoder> {
+        let file = match ::std::io::File::open(&Path::new(path)) {
+            Ok(f) => f,
+            Err(err) => { println!("{}", err); return None; }
+        };
+        let mut decoder = ::minimp3::Decoder::new(file);
+        match decoder.next_frame() {
+            Ok(frame) => {
+                let channels = frame.channels as i32;
+                let sample_rate = frame.sample_rate as i32;
+                Some(Mp3Decoder { decoder: decoder, pending: frame.data, channels: channels, sample_rate: sample_rate })
+            },
+            Err(err) => { println!("{}", err); None }
+        }
+    }
+}
+
+impl Decoder for Mp3Decoder {
+    fn read_samples(&mut self, out: &mut [i16]) -> uint {
+        let mut written = 0u;
+        while written < out.len() {
+            if self.pending.len() == 0 {
+                match self.decoder.next_frame() {
+                    Ok(frame) => self.pending = frame.data,
+                    Err(_) => break
+                }
+            }
+            let n = ::std::cmp::min(out.len() - written, self.pending.len());
+            for i in range(0u, n) {
+                out[written + i] = self.pending[i];
+            }
+            written += n;
+            self.pending = self.pending.slice_from(n).to_vec();
+        }
+        written
+    }
+
+    fn channels(&self) -> i32 { self.channels }
+    fn sample_rate(&self) -> i32 { self.sample_rate }
+    fn frames(&self) -> i64 { 0 }
+}
+
+/**
+ * Pick a decoder for `path` based on its extension.
+ *
+ * `.flac`, `.ogg` and `.mp3` are routed to their pure-Rust backends;
+ * everything else (notably `.wav` and `.aiff`) falls back to the
+ * existing libsndfile path.
+ *
+ * # Return
+ * An Option with Some(box Decoder) if a decoder could be opened for
+ * `path`, or None if an error has occured.
+ */
+pub fn decoder_for_path(path: &str) -> Option<Box<Decoder>> {
+    let ext = Path::new(path).extension_str().unwrap_or("").to_ascii_lower();
+
+    match ext.as_slice() {
+        "flac" => FlacDecoder::new(path).map(|d| box d as Box<Decoder>),
+        "ogg"  => OggDecoder::new(path).map(|d| box d as Box<Decoder>),
+        "mp3"  => Mp3Decoder::new(path).map(|d| box d as Box<Decoder>),
+        _      => SndFileDecoder::new(path).map(|d| box d as Box<Decoder>)
+    }
+}
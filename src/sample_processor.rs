@@ -0,0 +1,152 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2013 Jeremy Letang (letang.jeremy@gmail.com)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Offline sample processing, run once at load time rather than per-frame,
+//! so the realtime OpenAL playback path stays untouched.
+
+/**
+ * A transform applied to the interleaved samples of a `SoundData` before
+ * they're uploaded to OpenAL.
+ *
+ * `process` runs exactly once, at load time, against the whole buffer --
+ * there's no streaming or realtime budget to respect, so implementations
+ * can be as simple as a single pass over the slice.
+ */
+pub trait SampleProcessor {
+    /**
+     * Transform `samples` in place.
+     *
+     * `channels` is mutable and `samples` a `Vec` (rather than a fixed
+     * slice) so a processor that changes the layout -- a downmix, say --
+     * can truncate the buffer and update the channel count to match;
+     * `sample_rate` never changes within a single load, so it's passed
+     * by value for reference only.
+     *
+     * # Arguments
+     * * `samples` - The interleaved samples, one frame after another
+     *   (`[l, r, l, r, ...]` for stereo)
+     * * `channels` - Number of interleaved channels in `samples`
+     * * `sample_rate` - Sample rate of `samples`, in Hz
+     */
+    fn process(&mut self, samples: &mut Vec<i16>, channels: &mut i32, sample_rate: i32) -> ();
+}
+
+/// Scales every sample so the loudest one in the buffer reaches (but
+/// doesn't clip) full scale.
+pub struct PeakNormalize;
+
+impl SampleProcessor for PeakNormalize {
+    fn process(&mut self, samples: &mut Vec<i16>, _channels: &mut i32, _sample_rate: i32) -> () {
+        let peak = samples.iter().fold(0i16, |acc, &s| {
+            let abs = if s == i16::MIN { i16::MAX } else { s.abs() };
+            if abs > acc { abs } else { acc }
+        });
+
+        if peak == 0 {
+            return;
+        }
+
+        let gain = i16::MAX as f32 / peak as f32;
+        for s in samples.mut_iter() {
+            *s = (*s as f32 * gain) as i16;
+        }
+    }
+}
+
+/// Downmixes interleaved stereo samples to mono by averaging each pair
+/// of channels, shrinking the buffer to half its length and updating
+/// the channel count to 1.
+pub struct StereoToMono;
+
+impl SampleProcessor for StereoToMono {
+    fn process(&mut self, samples: &mut Vec<i16>, channels: &mut i32, _sample_rate: i32) -> () {
+        if *channels != 2 {
+            return;
+        }
+
+        let frames = samples.len() / 2;
+        {
+            let slice = samples.as_mut_slice();
+            for i in range(0u, frames) {
+                let l = slice[i * 2] as i32;
+                let r = slice[i * 2 + 1] as i32;
+                slice[i] = ((l + r) / 2) as i16;
+            }
+        }
+        samples.truncate(frames);
+        *channels = 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SampleProcessor, PeakNormalize, StereoToMono};
+
+    /// Samples scale by a float gain, so allow +/-1 of rounding slack.
+    fn approx_eq(a: i16, b: i16) -> bool {
+        (a as i32 - b as i32).abs() <= 1
+    }
+
+    #[test]
+    fn peak_normalize_scales_to_full_scale() -> () {
+        let mut samples = vec!(8192i16, -16384i16, 4096i16);
+        let mut channels = 1i32;
+        PeakNormalize.process(&mut samples, &mut channels, 44100);
+
+        // the loudest sample should now sit at (or right at) full scale
+        assert!(approx_eq(samples.as_slice()[1], -32767i16));
+        // every other sample keeps the same ratio to the peak
+        assert!(approx_eq(samples.as_slice()[0], 16384i16));
+        assert!(approx_eq(samples.as_slice()[2], 8192i16));
+    }
+
+    #[test]
+    fn peak_normalize_leaves_silence_untouched() -> () {
+        let mut samples = vec!(0i16, 0i16);
+        let mut channels = 1i32;
+        PeakNormalize.process(&mut samples, &mut channels, 44100);
+
+        assert_eq!(samples.as_slice()[0], 0i16);
+        assert_eq!(samples.as_slice()[1], 0i16);
+    }
+
+    #[test]
+    fn stereo_to_mono_averages_channels_and_updates_count() -> () {
+        let mut samples = vec!(10i16, 20i16, -10i16, 10i16);
+        let mut channels = 2i32;
+        StereoToMono.process(&mut samples, &mut channels, 44100);
+
+        assert_eq!(channels, 1);
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples.as_slice()[0], 15i16);
+        assert_eq!(samples.as_slice()[1], 0i16);
+    }
+
+    #[test]
+    fn stereo_to_mono_ignores_non_stereo_input() -> () {
+        let mut samples = vec!(1i16, 2i16, 3i16);
+        let mut channels = 1i32;
+        StereoToMono.process(&mut samples, &mut channels, 44100);
+
+        assert_eq!(channels, 1);
+        assert_eq!(samples.len(), 3);
+    }
+}
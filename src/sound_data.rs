@@ -22,13 +22,41 @@
 //! The datas extracted from a sound file.
 
 use std::mem;
+use std::io::MemReader;
 use libc::c_void;
 use std::vec::Vec;
 
 use openal::{ffi, al};
 use sndfile::{SndFile, SndInfo, Read};
+use sndfile::ffi as snd_ffi;
 use internal::OpenAlData;
 use audio_tags::{Tags, AudioTags, get_sound_tags};
+use sample_processor::SampleProcessor;
+use decoder;
+use decoder::Decoder;
+
+/// Number of samples read from a pure-Rust `Decoder` per chunk while
+/// draining it into the upload buffer.
+static DECODE_CHUNK_SAMPLES: uint = 32768;
+
+/// Does this sndfile subtype store its samples as floating point
+/// (`float64.wav`-style files) rather than integer PCM?
+fn is_float_subtype(format: i32) -> bool {
+    let subtype = format & snd_ffi::SF_FORMAT_SUBMASK;
+    subtype == snd_ffi::SF_FORMAT_FLOAT || subtype == snd_ffi::SF_FORMAT_DOUBLE
+}
+
+/// `.flac`, `.ogg` and `.mp3` are routed through a pure-Rust `Decoder`;
+/// everything else (notably `.wav`/`.aiff`) keeps going through
+/// libsndfile directly, so it gets the float32-native path from
+/// `from_sndfile`.
+fn has_sndfile_extension(path: &str) -> bool {
+    let ext = Path::new(path).extension_str().unwrap_or("").to_ascii_lower();
+    match ext.as_slice() {
+        "flac" | "ogg" | "mp3" => false,
+        _ => true
+    }
+}
 
 /**
  * Samples extracted from a file.
@@ -88,25 +116,189 @@ impl SoundData {
     pub fn new(path: &str) -> Option<SoundData> {
         check_openal_context!(None);
 
-        let mut file;
+        if has_sndfile_extension(path) {
+            match SndFile::new(path, Read) {
+                Ok(file) => SoundData::from_sndfile(file),
+                Err(err) => { println!("{}", err); None }
+            }
+        } else {
+            SoundData::from_decoder_path(path)
+        }
+    }
+
+    /**
+     * Create a new SoundData from an in-memory byte buffer.
+     *
+     * Handy for assets embedded with `include_bytes!` or downloaded at
+     * runtime, where there's no path on disk to hand to `new`.
+     *
+     * # Arguments
+     * * `bytes` - The whole encoded file, e.g. the contents of a .wav
+     *
+     * # Return
+     * An Option with Some(SoundData) if the SoundData is create, or None if
+     * an error has occured.
+     */
+    pub fn from_bytes(bytes: &[u8]) -> Option<SoundData> {
+        SoundData::from_reader(MemReader::new(bytes.to_vec()))
+    }
+
+    /**
+     * Create a new SoundData from an arbitrary seekable reader.
+     *
+     * # Arguments
+     * * `reader` - A `Reader + Seek` positioned at the start of an
+     *   encoded sound file
+     *
+     * # Return
+     * An Option with Some(SoundData) if the SoundData is create, or None if
+     * an error has occured.
+     */
+    pub fn from_reader<R: Reader + Seek + 'static>(reader: R) -> Option<SoundData> {
+        check_openal_context!(None);
+
+        match SndFile::new_virtual(box reader, Read) {
+            Ok(file) => SoundData::from_sndfile(file),
+            Err(err) => { println!("{}", err); None }
+        }
+    }
+
+    /**
+     * Create a new SoundData, running `processor` over the decoded
+     * samples before they're uploaded to OpenAL.
+     *
+     * This is the place to bake in gain normalization, a mono downmix,
+     * or a simple filter: it runs once at load time, so it can't add
+     * overhead to the realtime playback path. Only applies to the
+     * i16 path: when `AL_EXT_FLOAT32` is present, float sources are
+     * uploaded as float32 and the processor is not run; without the
+     * extension they are converted to i16 and processed like the rest.
+     *
+     * # Arguments
+     * * `path` - The path of the file to load
+     * * `processor` - The transform to apply to the decoded samples
+     *
+     * # Return
+     * An Option with Some(SoundData) if the SoundData is create, or None if
+     * an error has occured.
+     */
+    pub fn new_with_processor(path: &str, processor: &mut SampleProcessor) -> Option<SoundData> {
+        check_openal_context!(None);
 
         match SndFile::new(path, Read) {
-            Ok(file_) => file = file_,
-            Err(err) => { println!("{}", err); return None; }
-        };
+            Ok(file) => SoundData::from_sndfile_processed(file, Some(processor)),
+            Err(err) => { println!("{}", err); None }
+        }
+    }
 
+    /// Decode an already-opened SndFile and upload its samples to OpenAL.
+    fn from_sndfile(file: SndFile) -> Option<SoundData> {
+        SoundData::from_sndfile_processed(file, None)
+    }
+
+    /// Decode an already-opened SndFile, optionally running `processor`
+    /// over the samples before uploading them to OpenAL.
+    fn from_sndfile_processed(mut file: SndFile, processor: Option<&mut SampleProcessor>) -> Option<SoundData> {
         let infos = file.get_sndinfo();
 
         let nb_sample = infos.channels as i64 * infos.frames;
 
-        let mut samples = Vec::from_elem(nb_sample as uint, 0i16);
-        file.read_i16(samples.as_mut_slice(), nb_sample as i64);
+        // Keep native precision for float sources (float64.wav and
+        // friends) instead of always rounding down to i16; everything
+        // else (8/24-bit PCM, A-law, ...) still goes through sndfile's
+        // own i16 conversion, same as before.
+        let is_float = is_float_subtype(infos.format);
 
         let mut buffer_id = 0;
-        let len = mem::size_of::<i16>() * (samples.len());
 
-        // Retrieve format informations
-        let format =  match al::get_channels_format(infos.channels) {
+        if is_float && al::alIsExtensionPresent("AL_EXT_FLOAT32") {
+            let mut samples = Vec::from_elem(nb_sample as uint, 0f32);
+            file.read_f32(samples.as_mut_slice(), nb_sample as i64);
+
+            let format = match al::get_format(infos.channels, al::Float32) {
+                Some(fmt) => fmt,
+                None => {
+                    println!("Internal error : unrecognized format.");
+                    return None;
+                }
+            };
+            let len = mem::size_of::<f32>() * (samples.len());
+
+            al::alGenBuffers(1, &mut buffer_id);
+            al::alBufferData(buffer_id,
+                             format,
+                             samples.as_ptr() as *c_void,
+                             len as i32,
+                             infos.samplerate);
+        } else {
+            let mut samples = Vec::from_elem(nb_sample as uint, 0i16);
+            file.read_i16(samples.as_mut_slice(), nb_sample as i64);
+
+            let mut channels = infos.channels;
+            match processor {
+                Some(processor) => processor.process(&mut samples, &mut channels, infos.samplerate),
+                None => {}
+            }
+
+            let format = match al::get_format(channels, al::Int16) {
+                Some(fmt) => fmt,
+                None => {
+                    println!("Internal error : unrecognized format.");
+                    return None;
+                }
+            };
+            let len = mem::size_of::<i16>() * (samples.len());
+
+            al::alGenBuffers(1, &mut buffer_id);
+            al::alBufferData(buffer_id,
+                             format,
+                             samples.as_ptr() as *c_void,
+                             len as i32,
+                             infos.samplerate);
+        }
+
+        match al::openal_has_error() {
+            Some(err)   => { println!("{}", err); return None; },
+            None        => {}
+        };
+
+        let sound_data = SoundData {
+            sound_tags  : get_sound_tags(&file),
+            snd_info    : infos,
+            nb_sample   : nb_sample,
+            al_buffer   : buffer_id
+        };
+        file.close();
+
+        Some(sound_data)
+    }
+
+    /// Pick a pure-Rust `Decoder` for `path` (FLAC/OGG/MP3), drain it
+    /// into an i16 sample vector chunk by chunk, and upload exactly as
+    /// the libsndfile path does.
+    fn from_decoder_path(path: &str) -> Option<SoundData> {
+        let mut decoder = match decoder::decoder_for_path(path) {
+            Some(d) => d,
+            None => {
+                println!("Internal error : no decoder available for {}.", path);
+                return None;
+            }
+        };
+
+        let channels = decoder.channels();
+        let samplerate = decoder.sample_rate();
+
+        let mut samples = Vec::new();
+        let mut chunk = Vec::from_elem(DECODE_CHUNK_SAMPLES, 0i16);
+        loop {
+            let read = decoder.read_samples(chunk.as_mut_slice());
+            if read == 0 {
+                break;
+            }
+            samples.push_all(chunk.as_slice().slice_to(read));
+        }
+
+        let format = match al::get_format(channels, al::Int16) {
             Some(fmt) => fmt,
             None => {
                 println!("Internal error : unrecognized format.");
@@ -114,27 +306,45 @@ impl SoundData {
             }
         };
 
+        let mut buffer_id = 0;
+        let len = mem::size_of::<i16>() * (samples.len());
+
         al::alGenBuffers(1, &mut buffer_id);
         al::alBufferData(buffer_id,
                          format,
                          samples.as_ptr() as *c_void,
                          len as i32,
-                         infos.samplerate);
+                         samplerate);
 
         match al::openal_has_error() {
             Some(err)   => { println!("{}", err); return None; },
             None        => {}
         };
 
-        let sound_data = SoundData {
-            sound_tags  : get_sound_tags(&file),
-            snd_info    : infos,
-            nb_sample   : nb_sample,
-            al_buffer   : buffer_id
+        let nb_sample = samples.len() as i64;
+        let frames = if channels > 0 { nb_sample / channels as i64 } else { 0 };
+
+        // FLAC/OGG/MP3 don't go through libsndfile for decoding, but its
+        // tag readers still double as a best-effort metadata source when
+        // it happens to understand the container too.
+        let sound_tags = match SndFile::new(path, Read) {
+            Ok(file) => get_sound_tags(&file),
+            Err(_) => Tags::new()
         };
-        file.close();
 
-        Some(sound_data)
+        Some(SoundData {
+            sound_tags  : sound_tags,
+            snd_info    : SndInfo {
+                frames      : frames,
+                samplerate  : samplerate,
+                channels    : channels,
+                format      : 0,
+                sections    : 1,
+                seekable    : 0
+            },
+            nb_sample   : nb_sample,
+            al_buffer   : buffer_id
+        })
     }
 }
 
@@ -0,0 +1,120 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2013 Jeremy Letang (letang.jeremy@gmail.com)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! A cache that keeps one decoded SoundData per path, so preloading a
+//! level doesn't re-decode and re-upload the same file for every Sound
+//! that uses it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::fs::PathExtensions;
+use std::path::Path;
+use std::rc::Rc;
+
+use sound_data::SoundData;
+
+/**
+ * A path-keyed cache of shared SoundData instances.
+ *
+ * `SoundData` is already meant to be shared between several `Sound`s via
+ * `Rc<RefCell<SoundData>>`, but nothing stopped two call sites from each
+ * decoding and uploading their own copy of the same file. A
+ * `SoundDataCache` makes the sharing explicit: `load` decodes once per
+ * distinct path and hands out clones of the same `Rc` on every
+ * subsequent call.
+ *
+ * The cache is a plain value you create and own, not a global registry
+ * -- that keeps it consistent with the rest of the crate's
+ * single-threaded `Rc`-based sharing model, and lets you drop it (or
+ * `clear` it) to release the OpenAL buffers when a level unloads.
+ *
+ * # Example
+ * ```
+ * extern crate ears;
+ * use ears::{Sound, SoundDataCache};
+ *
+ * fn main() -> () {
+ *   let mut cache = SoundDataCache::new();
+ *   let snd_data = cache.load("path/to/my/sound.wav").unwrap();
+ *   let snd1 = Sound::new_with_data(snd_data.clone()).unwrap();
+ * }
+ * ```
+ */
+pub struct SoundDataCache {
+    entries: HashMap<String, Rc<RefCell<SoundData>>>
+}
+
+impl SoundDataCache {
+    /// Create a new, empty SoundDataCache.
+    pub fn new() -> SoundDataCache {
+        SoundDataCache { entries: HashMap::new() }
+    }
+
+    /**
+     * Get the SoundData for `path`, decoding it only if this is the
+     * first time `path` has been requested.
+     *
+     * # Arguments
+     * * `path` - The path of the file to load
+     *
+     * # Return
+     * An Option with Some(Rc<RefCell<SoundData>>) if the SoundData was
+     * already cached or could be created, or None if an error has
+     * occured while decoding.
+     */
+    pub fn load(&mut self, path: &str) -> Option<Rc<RefCell<SoundData>>> {
+        let key = canonical_key(path);
+
+        if let Some(data) = self.entries.find(&key) {
+            return Some(data.clone());
+        }
+
+        match SoundData::new(path) {
+            Some(data) => {
+                let data = Rc::new(RefCell::new(data));
+                self.entries.insert(key, data.clone());
+                Some(data)
+            },
+            None => None
+        }
+    }
+
+    /// Drop the cached entry for `path`, releasing its OpenAL buffer
+    /// once every `Sound` still holding the `Rc` has gone away.
+    pub fn evict(&mut self, path: &str) -> () {
+        self.entries.remove(&canonical_key(path));
+    }
+
+    /// Drop every cached entry, releasing all buffers whose `Sound`s
+    /// have already gone away.
+    pub fn clear(&mut self) -> () {
+        self.entries.clear();
+    }
+}
+
+/// Canonicalize `path` so the same file reached through two different
+/// (but equivalent) strings hits the same cache entry.
+fn canonical_key(path: &str) -> String {
+    match Path::new(path).canonicalize() {
+        Ok(canon) => canon.display().to_string(),
+        Err(_) => path.to_string()
+    }
+}
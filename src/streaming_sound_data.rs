@@ -0,0 +1,242 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2013 Jeremy Letang (letang.jeremy@gmail.com)
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Streamed samples extracted from a file, chunk by chunk.
+
+use std::mem;
+use libc::c_void;
+use std::vec::Vec;
+
+use openal::{ffi, al};
+use sndfile::{SndFile, SndInfo, Read};
+use internal::OpenAlData;
+
+/// Number of OpenAL buffers kept in flight for a streamed sound.
+static NB_STREAM_BUFFERS: uint = 4;
+/// Number of frames read from disk per chunk.
+static STREAM_CHUNK_FRAMES: i64 = 32768;
+
+/**
+ * Samples streamed from a file, chunk by chunk, instead of loaded whole.
+ *
+ * StreamingSoundData keeps the underlying file open and cycles a small
+ * ring of OpenAL buffers so that large files (music tracks, long
+ * ambiances) don't have to be decoded and uploaded in one shot. Unlike
+ * SoundData it isn't meant to be shared between several Sounds: each
+ * StreamingSoundData owns its file handle and its own buffer queue.
+ *
+ * # Example
+ * ```
+ * extern crate ears;
+ * use ears::StreamingSoundData;
+ *
+ * fn main() -> () {
+ *   let mut snd_data = StreamingSoundData::new("path/to/my/music.ogg").unwrap();
+ *   // feed the queue periodically, e.g. once per frame
+ *   snd_data.refill();
+ * }
+ * ```
+ */
+pub struct StreamingSoundData {
+    file: SndFile,
+    snd_info: SndInfo,
+    al_buffers: Vec<u32>,
+    al_source: u32,
+    looping: bool,
+    eof: bool
+}
+
+impl StreamingSoundData {
+    /**
+     * Create a new StreamingSoundData.
+     *
+     * Opens the file, fills every buffer of the ring with an initial
+     * chunk, and queues them on a freshly-generated source.
+     *
+     * # Arguments
+     * * `path` - The path of the file to stream
+     *
+     * # Return
+     * An Option with Some(StreamingSoundData) if the file could be
+     * opened and the initial buffers filled, or None if an error
+     * has occured.
+     */
+    pub fn new(path: &str) -> Option<StreamingSoundData> {
+        check_openal_context!(None);
+
+        let mut file;
+
+        match SndFile::new(path, Read) {
+            Ok(file_) => file = file_,
+            Err(err) => { println!("{}", err); return None; }
+        };
+
+        let infos = file.get_sndinfo();
+
+        let format = match al::get_format(infos.channels, al::Int16) {
+            Some(fmt) => fmt,
+            None => {
+                println!("Internal error : unrecognized format.");
+                return None;
+            }
+        };
+
+        let mut al_source = 0;
+        ffi::alGenSources(1, &mut al_source);
+
+        let mut al_buffers = Vec::from_elem(NB_STREAM_BUFFERS, 0u32);
+        ffi::alGenBuffers(NB_STREAM_BUFFERS as i32, al_buffers.as_mut_slice().as_mut_ptr());
+
+        let mut snd_data = StreamingSoundData {
+            file: file,
+            snd_info: infos,
+            al_buffers: al_buffers,
+            al_source: al_source,
+            looping: false,
+            eof: false
+        };
+
+        for i in range(0u, NB_STREAM_BUFFERS) {
+            let buffer_id = snd_data.al_buffers.get(i).clone();
+            if !snd_data.fill_buffer(buffer_id, format) {
+                break;
+            }
+            unsafe { ffi::alSourceQueueBuffers(snd_data.al_source, 1, &buffer_id); }
+        }
+
+        match al::openal_has_error() {
+            Some(err)   => { println!("{}", err); return None; },
+            None        => {}
+        };
+
+        Some(snd_data)
+    }
+
+    /**
+     * Read the next chunk of frames from the file into the given buffer
+     * and upload it to OpenAL.
+     *
+     * # Return
+     * true if samples were read and uploaded, false if the end of the
+     * file was reached and nothing was written.
+     */
+    fn fill_buffer(&mut self, buffer_id: u32, format: i32) -> bool {
+        let nb_sample = (self.snd_info.channels as i64 * STREAM_CHUNK_FRAMES) as uint;
+        let mut samples = Vec::from_elem(nb_sample, 0i16);
+        let read = self.file.read_i16(samples.as_mut_slice(), nb_sample as i64);
+
+        if read == 0 {
+            self.eof = true;
+            return false;
+        }
+
+        let len = mem::size_of::<i16>() * (read as uint);
+        al::alBufferData(buffer_id,
+                         format,
+                         samples.as_ptr() as *c_void,
+                         len as i32,
+                         self.snd_info.samplerate);
+        true
+    }
+
+    /**
+     * Set whether the stream should seek back to the start of the file
+     * and keep streaming once the end is reached, instead of stopping.
+     */
+    pub fn set_looping(&mut self, looping: bool) -> () {
+        self.looping = looping;
+    }
+
+    /**
+     * Poll the source for processed buffers, refill and requeue them.
+     *
+     * This must be called periodically (e.g. once per game frame or from
+     * a dedicated thread) while the stream is playing, otherwise the
+     * source will run dry and silently stop once its queued buffers have
+     * all been consumed.
+     */
+    pub fn refill(&mut self) -> () {
+        let format = match al::get_format(self.snd_info.channels, al::Int16) {
+            Some(fmt) => fmt,
+            None => return
+        };
+
+        let mut processed = 0;
+        unsafe { ffi::alGetSourcei(self.al_source, ffi::AL_BUFFERS_PROCESSED, &mut processed); }
+
+        for _ in range(0, processed) {
+            let mut buffer_id = 0;
+            unsafe { ffi::alSourceUnqueueBuffers(self.al_source, 1, &mut buffer_id); }
+
+            if self.eof {
+                if self.looping {
+                    self.file.seek(0, ::sndfile::SeekSet);
+                    self.eof = false;
+                } else {
+                    continue;
+                }
+            }
+
+            if self.fill_buffer(buffer_id, format) {
+                unsafe { ffi::alSourceQueueBuffers(self.al_source, 1, &buffer_id); }
+            } else if self.looping {
+                // `fill_buffer` just discovered EOF on this very buffer;
+                // loop back to frame 0 and requeue it right away instead
+                // of dropping it and waiting for some *other* buffer to
+                // notice `self.eof` on a later call -- that would permanently
+                // shrink the ring by one buffer per loop-around.
+                self.file.seek(0, ::sndfile::SeekSet);
+                self.eof = false;
+                if self.fill_buffer(buffer_id, format) {
+                    unsafe { ffi::alSourceQueueBuffers(self.al_source, 1, &buffer_id); }
+                }
+            }
+        }
+
+        // Guard against the underrun edge case: the source can fall into
+        // AL_STOPPED on its own once every queued buffer has drained,
+        // even though we still have more data to give it.
+        let mut state = 0;
+        unsafe { ffi::alGetSourcei(self.al_source, ffi::AL_SOURCE_STATE, &mut state); }
+        let mut queued = 0;
+        unsafe { ffi::alGetSourcei(self.al_source, ffi::AL_BUFFERS_QUEUED, &mut queued); }
+        if state == ffi::AL_STOPPED && queued > 0 {
+            unsafe { ffi::alSourcePlay(self.al_source); }
+        }
+    }
+
+    /// Get the OpenAL identifier of the streaming source.
+    #[doc(hidden)]
+    pub fn get_source(&self) -> u32 {
+        self.al_source
+    }
+}
+
+impl Drop for StreamingSoundData {
+    /// Destroy all the resources attached to the StreamingSoundData
+    fn drop(&mut self) -> () {
+        unsafe {
+            ffi::alSourceStop(self.al_source);
+            ffi::alDeleteSources(1, &mut self.al_source);
+            ffi::alDeleteBuffers(NB_STREAM_BUFFERS as i32, self.al_buffers.as_mut_slice().as_mut_ptr());
+        }
+    }
+}